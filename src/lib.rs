@@ -1,5 +1,10 @@
 use std::net::UdpSocket;
 use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+/// Module containing a TFTP server that serves transfers against a caller-supplied backend
+pub mod server;
 
 /// Module containing the opcodes used by TFTP
 pub mod opcode {
@@ -13,12 +18,170 @@ pub mod opcode {
 	pub static OPCODE_ACK: [u8; 2] = [0, 4];
 	/// Error
 	pub static OPCODE_ERR: [u8; 2] = [0, 5];
+	/// Option acknowledgment (RFC 2347)
+	pub static OPCODE_OACK: [u8; 2] = [0, 6];
 }
 
 /// The null byte (0u8)
 static NULL: [u8; 1] = [0];
-/// "NETASCII" in bytes for optimization
-static NETASCII: [u8; 8] = [110, 101, 116, 97, 115, 99, 105, 105];
+/// The default DATA payload size (RFC 1350) used until a larger `blksize` is negotiated
+static DEFAULT_BLKSIZE: usize = 512;
+/// The read timeout (in seconds) used until a `timeout` option is negotiated
+static DEFAULT_TIMEOUT_SECS: u64 = 1;
+/// How many times a DATA/ACK is retransmitted before giving up with `TftpError::Timeout`
+static MAX_RETRIES: u32 = 5;
+/// The smallest `blksize` RFC 2348 allows to be negotiated
+static MIN_BLKSIZE: u16 = 8;
+/// The largest `blksize` RFC 2348 allows to be negotiated
+static MAX_BLKSIZE: u16 = 65464;
+
+/// The transfer mode advertised in the RRQ/WRQ mode field (RFC 1350)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+	/// Raw bytes, passed through untouched. Suitable for binary files/firmware (e.g. u-boot
+	/// image uploads), which is what most TFTP traffic actually is.
+	Octet,
+	/// Text, normalized to the wire's CRLF line endings per RFC 764.
+	NetAscii,
+}
+
+impl Mode {
+	/// The mode field as it appears on the wire
+	fn as_bytes(&self) -> &'static [u8] {
+		match self {
+			Mode::Octet => b"octet",
+			Mode::NetAscii => b"netascii",
+		}
+	}
+}
+
+/// Encodes `data` into netascii form (RFC 764): each LF becomes CRLF and each standalone CR
+/// is escaped as CR NUL
+fn to_netascii(data: &[u8]) -> Vec<u8> {
+	let mut out = Vec::with_capacity(data.len());
+
+	for &b in data {
+		match b {
+			b'\n' => out.extend_from_slice(b"\r\n"),
+			b'\r' => out.extend_from_slice(&[b'\r', 0]),
+			_ => out.push(b),
+		}
+	}
+
+	out
+}
+
+/// Decodes a netascii byte stream back to local form: CRLF becomes LF and CR NUL becomes CR
+fn from_netascii(data: &[u8]) -> Vec<u8> {
+	let mut out = Vec::with_capacity(data.len());
+
+	let mut bytes = data.iter().peekable();
+	while let Some(&b) = bytes.next() {
+		if b == b'\r' {
+			match bytes.peek() {
+				Some(&&b'\n') => { out.push(b'\n'); bytes.next(); },
+				Some(&&0) => { out.push(b'\r'); bytes.next(); },
+				_ => out.push(b'\r'),
+			}
+		} else {
+			out.push(b);
+		}
+	}
+
+	out
+}
+
+/// Options that can be negotiated with a server via the RFC 2347 option extension.
+///
+/// Fields left as `None` are simply omitted from the request. `put_file`/`get_file` are
+/// equivalent to calling `put_file_opts`/`get_file_opts` with `TftpOptions::default()`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TftpOptions {
+	/// Requested DATA payload size in bytes (RFC 2348). The server may reply with a smaller value.
+	pub blksize: Option<u16>,
+	/// Requested number of DATA blocks to pipeline before waiting for an ACK (RFC 7440). The
+	/// server may reply with a smaller value.
+	pub windowsize: Option<u16>,
+	/// Requested retransmission timeout in seconds (RFC 2349). The server may reply with a
+	/// different value; either way it drives the socket's read timeout.
+	pub timeout: Option<u8>,
+	/// Whether to negotiate the transfer size (RFC 2349). Any `Some` value requests it:
+	/// `put_file_opts` always sends the real payload length and `get_file_opts` always sends 0,
+	/// regardless of the value given here. Once negotiated, this field (as parsed from the
+	/// server's OACK) carries the size the server reported.
+	pub tsize: Option<u64>,
+}
+
+/// Appends the requested options to a RRQ/WRQ payload as `"name\0value\0"` pairs
+fn append_options(payload: &mut Vec<u8>, opts: &TftpOptions) {
+	if let Some(blksize) = opts.blksize {
+		// RFC 2348: a server must reject blksize outside [8, 65464], so don't even ask for one
+		let blksize = blksize.clamp(MIN_BLKSIZE, MAX_BLKSIZE);
+		payload.extend_from_slice(b"blksize");
+		payload.push(0);
+		payload.extend_from_slice(blksize.to_string().as_bytes());
+		payload.push(0);
+	}
+
+	if let Some(windowsize) = opts.windowsize {
+		payload.extend_from_slice(b"windowsize");
+		payload.push(0);
+		payload.extend_from_slice(windowsize.to_string().as_bytes());
+		payload.push(0);
+	}
+
+	if let Some(timeout) = opts.timeout {
+		payload.extend_from_slice(b"timeout");
+		payload.push(0);
+		payload.extend_from_slice(timeout.to_string().as_bytes());
+		payload.push(0);
+	}
+}
+
+/// Appends a `"tsize\0<value>\0"` pair to a RRQ/WRQ payload. Kept separate from `append_options`
+/// because the value sent is direction-specific (the real file size for a write, `0` for a read)
+/// rather than something that can be read directly off `TftpOptions`.
+fn append_tsize(payload: &mut Vec<u8>, value: u64) {
+	payload.extend_from_slice(b"tsize");
+	payload.push(0);
+	payload.extend_from_slice(value.to_string().as_bytes());
+	payload.push(0);
+}
+
+/// Parses the `name\0value\0` pairs of an OACK payload into the options the server accepted
+fn parse_oack(payload: &[u8]) -> TftpOptions {
+	let mut opts = TftpOptions::default();
+
+	let fields: Vec<&[u8]> = payload.split(|&b| b == 0).filter(|f| !f.is_empty()).collect();
+	for pair in fields.chunks(2) {
+		if let [name, value] = pair {
+			if name.eq_ignore_ascii_case(b"blksize") {
+				// RFC 2348: a value outside [8, 65464] is invalid and must not be honored
+				if let Ok(value) = String::from_utf8_lossy(value).parse::<u16>() {
+					if (MIN_BLKSIZE..=MAX_BLKSIZE).contains(&value) {
+						opts.blksize = Some(value);
+					}
+				}
+			} else if name.eq_ignore_ascii_case(b"windowsize") {
+				// RFC 7440: windowsize must be at least 1, or pipelining wedges forever
+				if let Ok(value @ 1..=u16::MAX) = String::from_utf8_lossy(value).parse::<u16>() {
+					opts.windowsize = Some(value);
+				}
+			} else if name.eq_ignore_ascii_case(b"timeout") {
+				// RFC 2349: timeout must be at least 1; 0 is illegal and rejected by the socket API
+				if let Ok(value @ 1..=u8::MAX) = String::from_utf8_lossy(value).parse::<u8>() {
+					opts.timeout = Some(value);
+				}
+			} else if name.eq_ignore_ascii_case(b"tsize") {
+				if let Ok(value) = String::from_utf8_lossy(value).parse::<u64>() {
+					opts.tsize = Some(value);
+				}
+			}
+		}
+	}
+
+	opts
+}
 
 /// A enum containg the possible errors returned by put_file and get_file
 #[derive(Debug)]
@@ -40,7 +203,11 @@ pub enum TftpError {
 	/// File already exists
 	FileAlreadyExists,
 	/// User does not exist
-	NoSuchUser
+	NoSuchUser,
+	/// No response was received after the maximum number of retransmissions
+	Timeout,
+	/// The given server address did not resolve to anything
+	AddressResolution,
 }
 
 impl TftpError {
@@ -60,56 +227,220 @@ impl TftpError {
 	}
 }
 
+/// Whether a `recv_from` error is a read timeout rather than a genuine socket failure
+fn is_timeout(err: &std::io::Error) -> bool {
+	matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+}
+
+/// Sets `sock`'s read timeout to `secs`, flooring at 1: `set_read_timeout` itself rejects a zero
+/// duration, and a caller-supplied or negotiated `timeout` of 0 would otherwise panic here
+fn set_read_timeout_secs(sock: &UdpSocket, secs: u64) {
+	sock.set_read_timeout(Some(Duration::from_secs(secs.max(1)))).unwrap();
+}
+
 fn send_ack(sock: &UdpSocket, block_num: &[u8], socket_addr: SocketAddr) {
 	let payload = [&opcode::OPCODE_ACK, block_num].concat();
-	
+
 	sock.send_to(&payload, socket_addr).unwrap();
 }
 
-/// Writes a file into the TFTP server
+/// Sends the DATA packet for `block`, slicing it out of `data` at the negotiated `blksize`
+fn send_data_block(sock: &UdpSocket, socket_addr: SocketAddr, data: &[u8], block: u16, blksize: usize) {
+	let start = (block as usize - 1) * blksize;
+	let end = (start + blksize).min(data.len());
+
+	sock.send_to(&[&opcode::OPCODE_DAT, &block.to_be_bytes(), &data[start..end]].concat(), socket_addr).unwrap();
+}
+
+/// Writes a file into the TFTP server at `server` in octet (binary) mode
 ///
 /// ```rust
+/// # use std::collections::HashMap;
+/// # use std::sync::{Arc, Mutex};
+/// # use tftp_lib::server::{serve, TftpBackend};
+/// #
+/// # #[derive(Clone)]
+/// # struct MemoryBackend(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+/// #
+/// # impl TftpBackend for MemoryBackend {
+/// #     fn read(&self, path: &str) -> Result<Vec<u8>, tftp_lib::TftpError> {
+/// #         self.0.lock().unwrap().get(path).cloned().ok_or(tftp_lib::TftpError::FileNotFound)
+/// #     }
+/// #     fn write(&self, path: &str, data: Vec<u8>) -> Result<(), tftp_lib::TftpError> {
+/// #         self.0.lock().unwrap().insert(path.to_string(), data);
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # let server_sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+/// # let server_addr = server_sock.local_addr().unwrap();
+/// # std::thread::spawn(move || serve(server_sock, MemoryBackend(Arc::new(Mutex::new(HashMap::new())))));
 /// use std::net::UdpSocket;
 ///
 /// use tftp_lib::put_file;
 ///
 /// let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
 ///
-/// put_file("pathname.txt", "Testing".as_bytes(), &sock);
+/// put_file("pathname.txt", "Testing".as_bytes(), &sock, server_addr).unwrap();
 /// ```
-pub fn put_file(path: &str, data: &[u8], sock: &UdpSocket) -> Result<(), TftpError> {
+pub fn put_file<A: ToSocketAddrs>(path: &str, data: &[u8], sock: &UdpSocket, server: A) -> Result<(), TftpError> {
+	put_file_opts(path, data, sock, server, Mode::Octet, TftpOptions::default())
+}
+
+/// Writes a file into the TFTP server at `server` in the given `Mode`, negotiating the given
+/// `TftpOptions`
+///
+/// ```rust
+/// # use std::collections::HashMap;
+/// # use std::sync::{Arc, Mutex};
+/// # use tftp_lib::server::{serve, TftpBackend};
+/// #
+/// # #[derive(Clone)]
+/// # struct MemoryBackend(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+/// #
+/// # impl TftpBackend for MemoryBackend {
+/// #     fn read(&self, path: &str) -> Result<Vec<u8>, tftp_lib::TftpError> {
+/// #         self.0.lock().unwrap().get(path).cloned().ok_or(tftp_lib::TftpError::FileNotFound)
+/// #     }
+/// #     fn write(&self, path: &str, data: Vec<u8>) -> Result<(), tftp_lib::TftpError> {
+/// #         self.0.lock().unwrap().insert(path.to_string(), data);
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # let server_sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+/// # let server_addr = server_sock.local_addr().unwrap();
+/// # std::thread::spawn(move || serve(server_sock, MemoryBackend(Arc::new(Mutex::new(HashMap::new())))));
+/// use std::net::UdpSocket;
+///
+/// use tftp_lib::{put_file_opts, Mode, TftpOptions};
+///
+/// let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+///
+/// put_file_opts("pathname.txt", "Testing".as_bytes(), &sock, server_addr, Mode::Octet, TftpOptions { blksize: Some(1428), ..Default::default() }).unwrap();
+/// ```
+pub fn put_file_opts<A: ToSocketAddrs>(path: &str, data: &[u8], sock: &UdpSocket, server: A, mode: Mode, opts: TftpOptions) -> Result<(), TftpError> {
+	// Resolve once: every packet of the request (including retransmissions of it) targets this address
+	let server = server.to_socket_addrs().map_err(|_| TftpError::AddressResolution)?.next().ok_or(TftpError::AddressResolution)?;
+
 	// Better performance by ~40ns
-	let payload = [&opcode::OPCODE_WRQ, path.as_bytes(), &NULL, &NETASCII, &NULL].concat();
-	
-	sock.send_to(&payload, "127.0.0.1:69").unwrap();
+	let mut payload = [&opcode::OPCODE_WRQ, path.as_bytes(), &NULL, mode.as_bytes(), &NULL].concat();
+	append_options(&mut payload, &opts);
 
-	// Enter the loop managing the retrival of data
-	let mut sends_completed: u16 = 1;
-	let mut final_recv = false;
-	loop {
-		// Opcode (2b) + data (512b)
-		let mut response: [u8; 516] = [0u8; 516];
-		let (bytes, socket_addr) = sock.recv_from(&mut response).unwrap();
+	// netascii requires the wire's CRLF line endings; octet passes bytes through untouched
+	let data = match mode {
+		Mode::NetAscii => to_netascii(data),
+		Mode::Octet => data.to_vec(),
+	};
+	let data = &data[..];
 
-		match response[0..2]  {
-			// [0, 3] is OPCODE_DAT
-			[0, 4] => {
-				// Start with sending the file
-				if final_recv == true {
-					break;
+	// The real (wire-encoded) payload size, so the server can pre-check disk space
+	if opts.tsize.is_some() {
+		append_tsize(&mut payload, data.len() as u64);
+	}
+
+	let timeout_secs = opts.timeout.map(|t| t as u64).unwrap_or(DEFAULT_TIMEOUT_SECS);
+	set_read_timeout_secs(sock, timeout_secs);
+	sock.send_to(&payload, server).unwrap();
+
+	// Falls back to the RFC 1350 defaults until/unless the server OACKs larger values
+	let mut blksize = DEFAULT_BLKSIZE;
+	let mut window: u16 = 1;
+
+	// The first response is either an OACK (options accepted, in place of ACK 0) or a plain ACK 0
+	let socket_addr;
+	let mut retries = 0;
+	loop {
+		let mut response = vec![0u8; blksize + 4];
+		let (bytes, addr) = match sock.recv_from(&mut response) {
+			Ok(result) => result,
+			Err(e) if is_timeout(&e) => {
+				retries += 1;
+				if retries > MAX_RETRIES {
+					return Err(TftpError::Timeout);
 				}
+				sock.send_to(&payload, server).unwrap();
+				continue;
+			},
+			Err(e) => panic!("{}", e),
+		};
+		retries = 0;
 
-				if u16::from_be_bytes([response[2], response[3]]) == sends_completed {
-					sends_completed += 1;
+		match response[0..2] {
+			[0, 6] => {
+				let accepted = parse_oack(&response[2..bytes]);
+				if let Some(b) = accepted.blksize {
+					blksize = b as usize;
 				}
+				if let Some(w) = accepted.windowsize {
+					window = w;
+				}
+				if let Some(t) = accepted.timeout {
+					set_read_timeout_secs(sock, t as u64);
+				}
+				socket_addr = addr;
+				break;
+			},
+			[0, 4] if u16::from_be_bytes([response[2], response[3]]) == 0 => {
+				socket_addr = addr;
+				break;
+			},
+			[0, 5] => {
+				return Err(TftpError::from_error_code(&response[2..bytes]))
+			},
+			_ => {
+				return Err(TftpError::InvalidResponse(response.to_vec()))
+			}
+		}
+	}
+
+	// Block number of the final (possibly empty) DATA packet the transfer will end on
+	let last_block = (data.len() / blksize) as u16 + 1;
+
+	// `base` is the highest block number acknowledged so far, `next_send` the next one to send
+	let mut base: u16 = 0;
+	let mut next_send: u16 = 1;
+	while next_send <= last_block && next_send - base <= window {
+		send_data_block(sock, socket_addr, data, next_send, blksize);
+		next_send += 1;
+	}
 
-				let mut end = ((sends_completed) * 512) as usize;
-				if end > data.len() {
-					end = data.len();
-					final_recv = true;
+	while base < last_block {
+		let mut response = vec![0u8; blksize + 4];
+		let (bytes, addr) = match sock.recv_from(&mut response) {
+			Ok(result) => result,
+			Err(e) if is_timeout(&e) => {
+				retries += 1;
+				if retries > MAX_RETRIES {
+					return Err(TftpError::Timeout);
+				}
+				// Resend the whole outstanding window
+				for block in (base + 1)..next_send {
+					send_data_block(sock, socket_addr, data, block, blksize);
 				}
+				continue;
+			},
+			Err(e) => panic!("{}", e),
+		};
+		retries = 0;
+
+		// RFC 1350: all further packets must come from the TID the server opened its reply on
+		if addr != socket_addr {
+			return Err(TftpError::UnknownTransferID);
+		}
 
-				sock.send_to(&[&opcode::OPCODE_DAT, &sends_completed.to_be_bytes(), &data[((sends_completed-1)*512) as usize..end]].concat(), socket_addr).unwrap();
+		match response[0..2]  {
+			// [0, 4] is OPCODE_ACK
+			[0, 4] => {
+				// An ACK for fewer blocks than a full window is the new high-water mark: rewind
+				// and resend from there (the classic "sorcerer's apprentice" avoidance)
+				base = u16::from_be_bytes([response[2], response[3]]);
+				next_send = base + 1;
+
+				while next_send <= last_block && next_send - base <= window {
+					send_data_block(sock, socket_addr, data, next_send, blksize);
+					next_send += 1;
+				}
 			},
 			// [0, 5] is OPCODE_ERR
 			[0, 5] => {
@@ -120,14 +451,34 @@ pub fn put_file(path: &str, data: &[u8], sock: &UdpSocket) -> Result<(), TftpErr
 				return Err(TftpError::InvalidResponse(response.to_vec()))
 			}
 		}
-	};
+	}
 
 	Ok(())
 }
 
-/// Writes a file into the TFTP server
+/// Reads a file from the TFTP server at `server` in octet (binary) mode
 ///
 /// ```rust
+/// # use std::collections::HashMap;
+/// # use std::sync::{Arc, Mutex};
+/// # use tftp_lib::server::{serve, TftpBackend};
+/// #
+/// # #[derive(Clone)]
+/// # struct MemoryBackend(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+/// #
+/// # impl TftpBackend for MemoryBackend {
+/// #     fn read(&self, path: &str) -> Result<Vec<u8>, tftp_lib::TftpError> {
+/// #         self.0.lock().unwrap().get(path).cloned().ok_or(tftp_lib::TftpError::FileNotFound)
+/// #     }
+/// #     fn write(&self, path: &str, data: Vec<u8>) -> Result<(), tftp_lib::TftpError> {
+/// #         self.0.lock().unwrap().insert(path.to_string(), data);
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # let server_sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+/// # let server_addr = server_sock.local_addr().unwrap();
+/// # std::thread::spawn(move || serve(server_sock, MemoryBackend(Arc::new(Mutex::new(HashMap::new())))));
 /// use std::net::UdpSocket;
 ///
 /// # use tftp_lib::put_file;
@@ -135,32 +486,162 @@ pub fn put_file(path: &str, data: &[u8], sock: &UdpSocket) -> Result<(), TftpErr
 ///
 /// let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
 ///
-/// # put_file("pathname.txt", "Testing".as_bytes(), &sock);
-/// println!("{}", String::from_utf8_lossy(&get_file("pathname.txt", &sock).unwrap()));
+/// # put_file("pathname.txt", "Testing".as_bytes(), &sock, server_addr).unwrap();
+/// println!("{}", String::from_utf8_lossy(&get_file("pathname.txt", &sock, server_addr).unwrap()));
 /// ```
-pub fn get_file(path: &str, sock: &UdpSocket) -> Result<Vec<u8>, TftpError> {
+pub fn get_file<A: ToSocketAddrs>(path: &str, sock: &UdpSocket, server: A) -> Result<Vec<u8>, TftpError> {
+	get_file_opts(path, sock, server, Mode::Octet, TftpOptions::default()).map(|(data, _)| data)
+}
+
+/// Reads a file from the TFTP server at `server` in the given `Mode`, negotiating the given
+/// `TftpOptions`
+///
+/// Returns the file's contents alongside the transfer size the server reported (RFC 2349), if
+/// `tsize` was negotiated and the server honored it. Knowing the size up front lets a caller
+/// pre-allocate or report progress instead of growing the result block by block. Note that for
+/// `Mode::NetAscii` the reported size is the size on the wire (after CRLF expansion), which can
+/// be larger than `data.len()` of the decoded result returned alongside it.
+///
+/// ```rust
+/// # use std::collections::HashMap;
+/// # use std::sync::{Arc, Mutex};
+/// # use tftp_lib::server::{serve, TftpBackend};
+/// #
+/// # #[derive(Clone)]
+/// # struct MemoryBackend(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+/// #
+/// # impl TftpBackend for MemoryBackend {
+/// #     fn read(&self, path: &str) -> Result<Vec<u8>, tftp_lib::TftpError> {
+/// #         self.0.lock().unwrap().get(path).cloned().ok_or(tftp_lib::TftpError::FileNotFound)
+/// #     }
+/// #     fn write(&self, path: &str, data: Vec<u8>) -> Result<(), tftp_lib::TftpError> {
+/// #         self.0.lock().unwrap().insert(path.to_string(), data);
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # let server_sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+/// # let server_addr = server_sock.local_addr().unwrap();
+/// # std::thread::spawn(move || serve(server_sock, MemoryBackend(Arc::new(Mutex::new(HashMap::new())))));
+/// use std::net::UdpSocket;
+///
+/// # use tftp_lib::put_file;
+/// use tftp_lib::{get_file_opts, Mode, TftpOptions};
+///
+/// let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+///
+/// # put_file("pathname.txt", "Testing".as_bytes(), &sock, server_addr).unwrap();
+/// let (data, tsize) = get_file_opts("pathname.txt", &sock, server_addr, Mode::Octet, TftpOptions { blksize: Some(1428), ..Default::default() }).unwrap();
+/// println!("{} ({:?} bytes reported)", String::from_utf8_lossy(&data), tsize);
+/// ```
+pub fn get_file_opts<A: ToSocketAddrs>(path: &str, sock: &UdpSocket, server: A, mode: Mode, opts: TftpOptions) -> Result<(Vec<u8>, Option<u64>), TftpError> {
+	// Resolve once: every packet of the request (including retransmissions of it) targets this address
+	let server = server.to_socket_addrs().map_err(|_| TftpError::AddressResolution)?.next().ok_or(TftpError::AddressResolution)?;
+
 	// Better performance by ~40ns
-	let payload = [&opcode::OPCODE_RRQ, path.as_bytes(), &NULL, &NETASCII, &NULL].concat();
-	
-	sock.send_to(&payload, "127.0.0.1:69").unwrap();
+	let mut payload = [&opcode::OPCODE_RRQ, path.as_bytes(), &NULL, mode.as_bytes(), &NULL].concat();
+	append_options(&mut payload, &opts);
+
+	// We don't know the file size yet, so request it (RFC 2349); the server fills it in via OACK
+	if opts.tsize.is_some() {
+		append_tsize(&mut payload, 0);
+	}
+
+	let timeout_secs = opts.timeout.map(|t| t as u64).unwrap_or(DEFAULT_TIMEOUT_SECS);
+	set_read_timeout_secs(sock, timeout_secs);
+	sock.send_to(&payload, server).unwrap();
+
+	// Falls back to the RFC 1350 defaults until/unless the server OACKs larger values
+	let mut blksize = DEFAULT_BLKSIZE;
+	let mut window: u16 = 1;
 
 	// Enter the loop managing the retrival of data
 	let mut final_data = vec![];
+	let mut expected_block: u16 = 1;
+	let mut received_in_window: u16 = 0;
+
+	// Tracks what to resend on a timeout: the initial request until the server's first reply,
+	// then whatever ACK we last sent
+	let mut server_addr: Option<SocketAddr> = None;
+	let mut last_ack: [u8; 2] = [0, 0];
+	let mut retries = 0;
+	let mut tsize = None;
 	loop {
-		// Opcode (2b) + data (512b)
-		let mut response: [u8; 516] = [0u8; 516];
-		let (bytes, socket_addr) = sock.recv_from(&mut response).unwrap();
-		
-		//println!("{}", String::from_utf8_lossy(&response[4..]));
+		// Opcode (2b) + block number (2b) + data (up to blksize b)
+		let mut response = vec![0u8; blksize + 4];
+		let (bytes, socket_addr) = match sock.recv_from(&mut response) {
+			Ok(result) => result,
+			Err(e) if is_timeout(&e) => {
+				retries += 1;
+				if retries > MAX_RETRIES {
+					return Err(TftpError::Timeout);
+				}
+				match server_addr {
+					Some(addr) => send_ack(sock, &last_ack, addr),
+					None => { sock.send_to(&payload, server).unwrap(); },
+				}
+				continue;
+			},
+			Err(e) => panic!("{}", e),
+		};
+		retries = 0;
+
+		// RFC 1350: all further packets must come from the TID the server opened its reply on
+		match server_addr {
+			Some(addr) if addr != socket_addr => return Err(TftpError::UnknownTransferID),
+			_ => server_addr = Some(socket_addr),
+		}
 
 		match response[0..2]  {
+			// [0, 6] is OPCODE_OACK
+			[0, 6] => {
+				let accepted = parse_oack(&response[2..bytes]);
+				if let Some(b) = accepted.blksize {
+					blksize = b as usize;
+				}
+				if let Some(w) = accepted.windowsize {
+					window = w;
+				}
+				if let Some(t) = accepted.timeout {
+					set_read_timeout_secs(sock, t as u64);
+				}
+				if let Some(t) = accepted.tsize {
+					tsize = Some(t);
+				}
+
+				// Options negotiated in place of block 0, ACK it to start the data loop
+				last_ack = [0, 0];
+				send_ack(sock, &last_ack, socket_addr);
+			},
 			// [0, 3] is OPCODE_DAT
 			[0, 3] => {
-				// Start with reading the file
-				send_ack(sock, &response[2..4], socket_addr);
+				// A too-short packet can't carry a full opcode + block number
+				if bytes < 4 {
+					continue;
+				}
+
+				let block = u16::from_be_bytes([response[2], response[3]]);
+
+				if block != expected_block {
+					// Out of order: ACK the last good block to force the sender to rewind
+					last_ack = (expected_block - 1).to_be_bytes();
+					send_ack(sock, &last_ack, socket_addr);
+					received_in_window = 0;
+					continue;
+				}
 
-				final_data.extend(&response[4..]);
-				if bytes < 516 {
+				final_data.extend(&response[4..bytes]);
+				received_in_window += 1;
+				expected_block += 1;
+
+				let short = bytes < blksize + 4;
+				if short || received_in_window >= window {
+					last_ack = block.to_be_bytes();
+					send_ack(sock, &last_ack, socket_addr);
+					received_in_window = 0;
+				}
+
+				if short {
 					break;
 				}
 			},
@@ -175,5 +656,71 @@ pub fn get_file(path: &str, sock: &UdpSocket) -> Result<Vec<u8>, TftpError> {
 		}
 	};
 
-	Ok(final_data)
+	match mode {
+		Mode::NetAscii => Ok((from_netascii(&final_data), tsize)),
+		Mode::Octet => Ok((final_data, tsize)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Builds a `name\0value\0...` OACK payload out of `(name, value)` pairs
+	fn oack_payload(pairs: &[(&str, &str)]) -> Vec<u8> {
+		let mut payload = vec![];
+		for (name, value) in pairs {
+			payload.extend_from_slice(name.as_bytes());
+			payload.push(0);
+			payload.extend_from_slice(value.as_bytes());
+			payload.push(0);
+		}
+		payload
+	}
+
+	#[test]
+	fn parse_oack_accepts_in_range_values() {
+		let opts = parse_oack(&oack_payload(&[("blksize", "1024"), ("windowsize", "4"), ("timeout", "3"), ("tsize", "12345")]));
+		assert_eq!(opts.blksize, Some(1024));
+		assert_eq!(opts.windowsize, Some(4));
+		assert_eq!(opts.timeout, Some(3));
+		assert_eq!(opts.tsize, Some(12345));
+	}
+
+	#[test]
+	fn parse_oack_rejects_zero_blksize() {
+		assert_eq!(parse_oack(&oack_payload(&[("blksize", "0")])).blksize, None);
+	}
+
+	#[test]
+	fn parse_oack_rejects_oversized_blksize() {
+		assert_eq!(parse_oack(&oack_payload(&[("blksize", "65465")])).blksize, None);
+	}
+
+	#[test]
+	fn parse_oack_rejects_zero_windowsize() {
+		assert_eq!(parse_oack(&oack_payload(&[("windowsize", "0")])).windowsize, None);
+	}
+
+	#[test]
+	fn parse_oack_rejects_zero_timeout() {
+		assert_eq!(parse_oack(&oack_payload(&[("timeout", "0")])).timeout, None);
+	}
+
+	#[test]
+	fn append_options_clamps_out_of_range_blksize() {
+		let mut payload = vec![];
+		append_options(&mut payload, &TftpOptions { blksize: Some(0), ..Default::default() });
+		assert_eq!(parse_oack(&payload).blksize, Some(MIN_BLKSIZE));
+
+		let mut payload = vec![];
+		append_options(&mut payload, &TftpOptions { blksize: Some(u16::MAX), ..Default::default() });
+		assert_eq!(parse_oack(&payload).blksize, Some(MAX_BLKSIZE));
+	}
+
+	#[test]
+	fn netascii_roundtrip() {
+		let data = b"line one\nline two\rline three\n";
+		assert_eq!(from_netascii(&to_netascii(data)), data);
+	}
 }
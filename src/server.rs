@@ -0,0 +1,331 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use crate::{
+	append_options, append_tsize, from_netascii, is_timeout, opcode, parse_oack, send_ack,
+	send_data_block, set_read_timeout_secs, to_netascii, Mode, TftpError, TftpOptions,
+	DEFAULT_BLKSIZE, DEFAULT_TIMEOUT_SECS, MAX_RETRIES,
+};
+
+/// Storage backend a `serve` session reads and writes files through, e.g. the filesystem or an
+/// in-memory map
+pub trait TftpBackend {
+	/// Reads the full contents of `path` for a client read (RRQ)
+	fn read(&self, path: &str) -> Result<Vec<u8>, TftpError>;
+	/// Stores `data` at `path` for a client write (WRQ)
+	fn write(&self, path: &str, data: Vec<u8>) -> Result<(), TftpError>;
+	/// Whether the backend has room for an incoming write of `size` bytes, checked against the
+	/// client-declared `tsize` (RFC 2349) before a WRQ is accepted. The default allows anything;
+	/// override it to reject oversized transfers early with `TftpError::DiskFull` instead of
+	/// receiving the whole thing only to fail once `write` is finally called.
+	fn has_space(&self, _size: u64) -> bool {
+		true
+	}
+}
+
+/// Parses a RRQ/WRQ body (`path\0mode\0[opt\0val\0]...`) into its fields
+fn parse_request(body: &[u8]) -> Option<(String, Mode, TftpOptions)> {
+	let mut parts = body.splitn(3, |&b| b == 0);
+	let path = parts.next()?;
+	let mode = parts.next()?;
+	let rest = parts.next().unwrap_or(&[]);
+
+	let mode = if mode.eq_ignore_ascii_case(b"netascii") { Mode::NetAscii } else { Mode::Octet };
+
+	Some((String::from_utf8_lossy(path).to_string(), mode, parse_oack(rest)))
+}
+
+/// Sends an ERROR packet (`OPCODE_ERR` + code + message) for `err`
+fn send_error(sock: &UdpSocket, addr: SocketAddr, err: &TftpError) {
+	let (code, message): (u16, &str) = match err {
+		TftpError::FileNotFound => (1, "File not found"),
+		TftpError::AccessViolation => (2, "Access violation"),
+		TftpError::DiskFull => (3, "Disk full or allocation exceeded"),
+		TftpError::IllegalOperation => (4, "Illegal TFTP operation"),
+		TftpError::UnknownTransferID => (5, "Unknown transfer ID"),
+		TftpError::FileAlreadyExists => (6, "File already exists"),
+		TftpError::NoSuchUser => (7, "No such user"),
+		_ => (0, "Not defined"),
+	};
+
+	let payload = [&opcode::OPCODE_ERR, &code.to_be_bytes(), message.as_bytes(), &[0u8][..]].concat();
+	sock.send_to(&payload, addr).unwrap();
+}
+
+/// Listens on `sock` for RRQ/WRQ packets and serves each transfer against `backend`, spawning a
+/// fresh session socket (the TID) per request as RFC 1350 requires
+///
+/// ```rust,no_run
+/// use std::net::UdpSocket;
+/// use std::collections::HashMap;
+/// use std::sync::{Arc, Mutex};
+///
+/// use tftp_lib::TftpError;
+/// use tftp_lib::server::{serve, TftpBackend};
+///
+/// #[derive(Clone)]
+/// struct MemoryBackend(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+///
+/// impl TftpBackend for MemoryBackend {
+///     fn read(&self, path: &str) -> Result<Vec<u8>, TftpError> {
+///         self.0.lock().unwrap().get(path).cloned().ok_or(TftpError::FileNotFound)
+///     }
+///
+///     fn write(&self, path: &str, data: Vec<u8>) -> Result<(), TftpError> {
+///         self.0.lock().unwrap().insert(path.to_string(), data);
+///         Ok(())
+///     }
+/// }
+///
+/// let sock = UdpSocket::bind("0.0.0.0:69").unwrap();
+/// serve(sock, MemoryBackend(Arc::new(Mutex::new(HashMap::new()))));
+/// ```
+pub fn serve<B: TftpBackend + Clone + Send + 'static>(sock: UdpSocket, backend: B) -> ! {
+	loop {
+		let mut buf = vec![0u8; DEFAULT_BLKSIZE + 4];
+		let (bytes, client_addr) = match sock.recv_from(&mut buf) {
+			Ok(result) => result,
+			Err(_) => continue,
+		};
+		buf.truncate(bytes);
+
+		let backend = backend.clone();
+		std::thread::spawn(move || handle_request(&buf, client_addr, backend));
+	}
+}
+
+fn handle_request<B: TftpBackend>(request: &[u8], client_addr: SocketAddr, backend: B) {
+	let session_sock = match UdpSocket::bind("0.0.0.0:0") {
+		Ok(sock) => sock,
+		Err(_) => return,
+	};
+	session_sock.set_read_timeout(Some(Duration::from_secs(DEFAULT_TIMEOUT_SECS))).unwrap();
+
+	if request.len() < 2 {
+		return;
+	}
+
+	let (path, mode, opts) = match parse_request(&request[2..]) {
+		Some(parsed) => parsed,
+		None => return send_error(&session_sock, client_addr, &TftpError::IllegalOperation),
+	};
+
+	match request[0..2] {
+		// [0, 1] is OPCODE_RRQ: the client wants to read a file
+		[0, 1] => handle_read(&session_sock, client_addr, &path, mode, opts, &backend),
+		// [0, 2] is OPCODE_WRQ: the client wants to write a file
+		[0, 2] => handle_write(&session_sock, client_addr, &path, mode, opts, &backend),
+		_ => send_error(&session_sock, client_addr, &TftpError::IllegalOperation),
+	}
+}
+
+/// Serves a RRQ: sends `path`'s contents from `backend`, symmetric to the client's `get_file`
+fn handle_read<B: TftpBackend>(sock: &UdpSocket, client_addr: SocketAddr, path: &str, mode: Mode, opts: TftpOptions, backend: &B) {
+	let data = match backend.read(path) {
+		Ok(data) => data,
+		Err(err) => return send_error(sock, client_addr, &err),
+	};
+
+	let data = match mode {
+		Mode::NetAscii => to_netascii(&data),
+		Mode::Octet => data,
+	};
+	let data = &data[..];
+
+	let blksize = opts.blksize.unwrap_or(DEFAULT_BLKSIZE as u16) as usize;
+	let window = opts.windowsize.unwrap_or(1);
+	let has_options = opts.blksize.is_some() || opts.windowsize.is_some() || opts.timeout.is_some() || opts.tsize.is_some();
+
+	if let Some(timeout) = opts.timeout {
+		set_read_timeout_secs(sock, timeout as u64);
+	}
+
+	let send_oack = || {
+		let mut oack = opcode::OPCODE_OACK.to_vec();
+		append_options(&mut oack, &opts);
+		// The client sent a placeholder tsize of 0; echo back the real size of the data it's about to receive
+		if opts.tsize.is_some() {
+			append_tsize(&mut oack, data.len() as u64);
+		}
+		sock.send_to(&oack, client_addr).unwrap();
+	};
+
+	// Block number of the final (possibly empty) DATA packet the transfer will end on
+	let last_block = (data.len() / blksize) as u16 + 1;
+	let mut base: u16 = 0;
+	let mut next_send: u16 = 1;
+	// An OACK doubles as the ACK for "block 0"; DATA only starts once the client ACKs it
+	let mut awaiting_oack_ack = has_options;
+
+	if awaiting_oack_ack {
+		send_oack();
+	} else {
+		while next_send <= last_block && next_send - base <= window {
+			send_data_block(sock, client_addr, data, next_send, blksize);
+			next_send += 1;
+		}
+	}
+
+	let mut retries = 0;
+	while base < last_block {
+		let mut response = vec![0u8; blksize + 4];
+		let (bytes, addr) = match sock.recv_from(&mut response) {
+			Ok(result) => result,
+			Err(e) if is_timeout(&e) => {
+				retries += 1;
+				if retries > MAX_RETRIES {
+					return;
+				}
+				if awaiting_oack_ack {
+					send_oack();
+				} else {
+					for block in (base + 1)..next_send {
+						send_data_block(sock, client_addr, data, block, blksize);
+					}
+				}
+				continue;
+			},
+			Err(_) => return,
+		};
+		retries = 0;
+
+		if addr != client_addr {
+			return send_error(sock, client_addr, &TftpError::UnknownTransferID);
+		}
+
+		// A too-short packet can't carry a full opcode + block number
+		if bytes < 4 {
+			continue;
+		}
+
+		match response[0..2] {
+			// [0, 4] is OPCODE_ACK
+			[0, 4] => {
+				awaiting_oack_ack = false;
+				base = u16::from_be_bytes([response[2], response[3]]);
+				next_send = base + 1;
+
+				while next_send <= last_block && next_send - base <= window {
+					send_data_block(sock, client_addr, data, next_send, blksize);
+					next_send += 1;
+				}
+			},
+			_ => return,
+		}
+	}
+}
+
+/// Serves a WRQ: receives a file into `backend` at `path`, symmetric to the client's `put_file`
+fn handle_write<B: TftpBackend>(sock: &UdpSocket, client_addr: SocketAddr, path: &str, mode: Mode, opts: TftpOptions, backend: &B) {
+	if let Some(tsize) = opts.tsize {
+		if !backend.has_space(tsize) {
+			return send_error(sock, client_addr, &TftpError::DiskFull);
+		}
+	}
+
+	let blksize = opts.blksize.unwrap_or(DEFAULT_BLKSIZE as u16) as usize;
+	let window = opts.windowsize.unwrap_or(1);
+	let has_options = opts.blksize.is_some() || opts.windowsize.is_some() || opts.timeout.is_some() || opts.tsize.is_some();
+
+	if let Some(timeout) = opts.timeout {
+		set_read_timeout_secs(sock, timeout as u64);
+	}
+
+	let send_oack = || {
+		let mut oack = opcode::OPCODE_OACK.to_vec();
+		append_options(&mut oack, &opts);
+		// Echo the client's declared size back to confirm the transfer is accepted
+		if let Some(tsize) = opts.tsize {
+			append_tsize(&mut oack, tsize);
+		}
+		sock.send_to(&oack, client_addr).unwrap();
+	};
+
+	let mut last_ack: [u8; 2] = [0, 0];
+	// An OACK doubles as the ACK for "block 0"; until the client's first DATA block arrives, a
+	// timeout must resend the OACK rather than a plain ACK, or the client falls back to defaults
+	let mut awaiting_oack_ack = has_options;
+
+	if awaiting_oack_ack {
+		send_oack();
+	} else {
+		send_ack(sock, &last_ack, client_addr);
+	}
+
+	let mut data = vec![];
+	let mut expected_block: u16 = 1;
+	let mut received_in_window: u16 = 0;
+	let mut retries = 0;
+
+	loop {
+		let mut response = vec![0u8; blksize + 4];
+		let (bytes, addr) = match sock.recv_from(&mut response) {
+			Ok(result) => result,
+			Err(e) if is_timeout(&e) => {
+				retries += 1;
+				if retries > MAX_RETRIES {
+					return;
+				}
+				if awaiting_oack_ack {
+					send_oack();
+				} else {
+					send_ack(sock, &last_ack, client_addr);
+				}
+				continue;
+			},
+			Err(_) => return,
+		};
+		retries = 0;
+
+		if addr != client_addr {
+			return send_error(sock, client_addr, &TftpError::UnknownTransferID);
+		}
+
+		if bytes < 4 {
+			continue;
+		}
+
+		match response[0..2] {
+			// [0, 3] is OPCODE_DAT
+			[0, 3] => {
+				awaiting_oack_ack = false;
+				let block = u16::from_be_bytes([response[2], response[3]]);
+
+				if block != expected_block {
+					// Out of order: ACK the last good block to force the client to rewind
+					last_ack = (expected_block - 1).to_be_bytes();
+					send_ack(sock, &last_ack, client_addr);
+					received_in_window = 0;
+					continue;
+				}
+
+				data.extend_from_slice(&response[4..bytes]);
+				received_in_window += 1;
+				expected_block += 1;
+
+				let short = bytes < blksize + 4;
+				last_ack = block.to_be_bytes();
+				if short {
+					// The final block's ACK is the client's completion signal (see get_file's doc
+					// example, which does a put then a get right after) - defer it until the
+					// backend write below actually succeeds, or a client can race the write.
+					break;
+				} else if received_in_window >= window {
+					send_ack(sock, &last_ack, client_addr);
+					received_in_window = 0;
+				}
+			},
+			_ => return,
+		}
+	}
+
+	let data = match mode {
+		Mode::NetAscii => from_netascii(&data),
+		Mode::Octet => data,
+	};
+
+	match backend.write(path, data) {
+		Ok(()) => send_ack(sock, &last_ack, client_addr),
+		Err(err) => send_error(sock, client_addr, &err),
+	}
+}
@@ -0,0 +1,157 @@
+//! Loopback client/server roundtrips and adversarial fake-server tests.
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tftp_lib::server::{serve, TftpBackend};
+use tftp_lib::{get_file_opts, put_file_opts, Mode, TftpError, TftpOptions};
+
+#[derive(Clone)]
+struct MemoryBackend(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+
+impl MemoryBackend {
+	fn new() -> Self {
+		MemoryBackend(Arc::new(Mutex::new(HashMap::new())))
+	}
+}
+
+impl TftpBackend for MemoryBackend {
+	fn read(&self, path: &str) -> Result<Vec<u8>, TftpError> {
+		self.0.lock().unwrap().get(path).cloned().ok_or(TftpError::FileNotFound)
+	}
+
+	fn write(&self, path: &str, data: Vec<u8>) -> Result<(), TftpError> {
+		self.0.lock().unwrap().insert(path.to_string(), data);
+		Ok(())
+	}
+}
+
+/// Spawns a `serve` loop against a fresh `MemoryBackend` on an ephemeral loopback port
+fn spawn_server() -> SocketAddr {
+	let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let addr = sock.local_addr().unwrap();
+	std::thread::spawn(move || serve(sock, MemoryBackend::new()));
+	addr
+}
+
+fn roundtrip(opts: TftpOptions, data: &[u8]) {
+	let server = spawn_server();
+	let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+
+	put_file_opts("roundtrip.bin", data, &sock, server, Mode::Octet, opts).unwrap();
+	let (received, _) = get_file_opts("roundtrip.bin", &sock, server, Mode::Octet, opts).unwrap();
+
+	assert_eq!(received, data);
+}
+
+#[test]
+fn roundtrip_default_options() {
+	roundtrip(TftpOptions::default(), b"hello, tftp");
+}
+
+#[test]
+fn roundtrip_small_blksize_forces_multiple_blocks() {
+	let opts = TftpOptions { blksize: Some(8), ..Default::default() };
+	roundtrip(opts, &[0x42u8; 100]);
+}
+
+#[test]
+fn roundtrip_windowed_pipelining() {
+	let opts = TftpOptions { blksize: Some(16), windowsize: Some(4), ..Default::default() };
+	roundtrip(opts, &[0xABu8; 1000]);
+}
+
+#[test]
+fn roundtrip_tsize_is_reported_on_get() {
+	let data = vec![0x7Eu8; 300];
+	let server = spawn_server();
+	let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+	let opts = TftpOptions { tsize: Some(0), ..Default::default() };
+
+	put_file_opts("sized.bin", &data, &sock, server, Mode::Octet, opts).unwrap();
+	let (received, tsize) = get_file_opts("sized.bin", &sock, server, Mode::Octet, opts).unwrap();
+
+	assert_eq!(received, data);
+	assert_eq!(tsize, Some(data.len() as u64));
+}
+
+#[test]
+fn roundtrip_netascii_translates_line_endings() {
+	let data = b"one\ntwo\nthree";
+	let server = spawn_server();
+	let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+
+	put_file_opts("text.txt", data, &sock, server, Mode::NetAscii, TftpOptions::default()).unwrap();
+	let (received, _) = get_file_opts("text.txt", &sock, server, Mode::NetAscii, TftpOptions::default()).unwrap();
+
+	assert_eq!(received, data);
+}
+
+/// A one-shot fake server: replies to the first request with `reply`, then goes silent
+fn fake_server_reply_once(reply: Vec<u8>) -> SocketAddr {
+	let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let addr = sock.local_addr().unwrap();
+	std::thread::spawn(move || {
+		let mut buf = [0u8; 600];
+		if let Ok((_, client_addr)) = sock.recv_from(&mut buf) {
+			let _ = sock.send_to(&reply, client_addr);
+		}
+	});
+	addr
+}
+
+fn oack(pairs: &[(&str, &str)]) -> Vec<u8> {
+	let mut payload = vec![0, 6];
+	for (name, value) in pairs {
+		payload.extend_from_slice(name.as_bytes());
+		payload.push(0);
+		payload.extend_from_slice(value.as_bytes());
+		payload.push(0);
+	}
+	payload
+}
+
+/// Runs `call` and asserts it returns (an `Err` is fine) instead of panicking
+fn assert_no_panic<T>(call: impl FnOnce() -> Result<T, TftpError> + std::panic::UnwindSafe) {
+	let result = std::panic::catch_unwind(call);
+	assert!(result.is_ok(), "call panicked instead of returning a TftpError");
+}
+
+#[test]
+fn malformed_oack_zero_blksize_does_not_panic() {
+	let server = fake_server_reply_once(oack(&[("blksize", "0")]));
+	let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+	sock.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+	let opts = TftpOptions { blksize: Some(1024), ..Default::default() };
+
+	assert_no_panic(move || put_file_opts("x", b"data", &sock, server, Mode::Octet, opts));
+}
+
+#[test]
+fn malformed_oack_zero_windowsize_does_not_panic() {
+	let server = fake_server_reply_once(oack(&[("windowsize", "0")]));
+	let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+	sock.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+	let opts = TftpOptions { windowsize: Some(4), ..Default::default() };
+
+	assert_no_panic(move || put_file_opts("x", b"data", &sock, server, Mode::Octet, opts));
+}
+
+#[test]
+fn malformed_oack_zero_timeout_does_not_panic() {
+	let server = fake_server_reply_once(oack(&[("timeout", "0")]));
+	let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+	sock.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+	let opts = TftpOptions { timeout: Some(1), ..Default::default() };
+
+	assert_no_panic(move || put_file_opts("x", b"data", &sock, server, Mode::Octet, opts));
+}
+
+#[test]
+fn unresolvable_server_address_returns_err_not_panic() {
+	let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+	let result = get_file_opts("x", &sock, "this.host.does.not.exist.invalid:69", Mode::Octet, TftpOptions::default());
+	assert!(matches!(result, Err(TftpError::AddressResolution)));
+}